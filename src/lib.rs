@@ -34,7 +34,11 @@
 //! });
 //! ```
 //! See [scope_async](fn.scope_async.html) and [scope_async_local](fn.scope_async_local.html)
-//! as well for versions that work with `async` scopes.
+//! as well for versions that work with `async` scopes, [sync](sync/index.html) for a
+//! `Send + Sync` version that can register callbacks invoked from another thread, and
+//! the `unsafe` [scope_guaranteed](fn.scope_guaranteed.html) for a lower-overhead
+//! alternative to the runtime panic guard described below, for callers who can uphold its
+//! safety contract.
 //!
 //! # How is this safe?
 //! There are three important concepts in this implementation:
@@ -106,14 +110,30 @@ impl<'env, 'scope> Drop for Registered<'env, 'scope> {
 /// A `Scope` is used to register callbacks.
 /// See [Scope::register](struct.Scope.html#method.register).
 pub struct Scope<'env> {
-    callbacks: core::cell::RefCell<Vec<Rc<Deregister<'env>>>>,
+    callbacks: Rc<core::cell::RefCell<Vec<Rc<Deregister<'env>>>>>,
+    context: core::cell::RefCell<Vec<(core::any::TypeId, Box<dyn core::any::Any>)>>,
+    extern_c_allocs: core::cell::RefCell<Vec<Box<dyn FnOnce() + 'env>>>,
+    parent: Option<*const Scope<'env>>,
     marker: core::marker::PhantomData<&'env mut &'env ()>,
 }
 
 impl<'env> Scope<'env> {
     fn new() -> Self {
         Self {
-            callbacks: core::cell::RefCell::new(Vec::new()),
+            callbacks: Rc::new(core::cell::RefCell::new(Vec::new())),
+            context: core::cell::RefCell::new(Vec::new()),
+            extern_c_allocs: core::cell::RefCell::new(Vec::new()),
+            parent: None,
+            marker: core::marker::PhantomData,
+        }
+    }
+
+    fn new_child(parent: &Scope<'env>) -> Self {
+        Self {
+            callbacks: Rc::new(core::cell::RefCell::new(Vec::new())),
+            context: core::cell::RefCell::new(Vec::new()),
+            extern_c_allocs: core::cell::RefCell::new(Vec::new()),
+            parent: Some(parent as *const Scope<'env>),
             marker: core::marker::PhantomData,
         }
     }
@@ -154,14 +174,217 @@ impl<'env> Scope<'env> {
             marker: core::marker::PhantomData,
         }
     }
+
+    /// Register the function `c` with local lifetime `'env` for use with raw `extern "C"`
+    /// callback-registration APIs (the kind found in `libc`/`sys` bindings), which take a bare
+    /// function pointer plus an opaque user-data pointer rather than a `Box<dyn FnMut>`.
+    ///
+    /// `c` is boxed and leaked into a `*mut c_void`; `register` is handed the generated
+    /// trampoline together with that pointer to pass to the C side. The trampoline recovers
+    /// `c` from the pointer *by reference* on every call, so it never takes ownership mid-call.
+    /// `deregister` receives the same `H` handle as well as the `*mut c_void`, so it can be
+    /// handed back to the C de-registration API.
+    ///
+    /// As with [register](#method.register), if the `Registered` object is
+    /// `std::mem::forget`-ed, `Scope::drop` will perform the de-registration. Unlike
+    /// `register`, the C side only ever has the raw `data` pointer, not an `Rc` whose drop
+    /// the trampoline can observe, so there's no safe moment to free the allocation as soon
+    /// as `deregister` runs: `deregister` only clears the callback, and the (now-empty)
+    /// allocation stays live until `Scope::drop` frees it. So, as with `register`, if the
+    /// trampoline is invoked after `deregister` has run but before the scope is dropped, the
+    /// call will `panic!` rather than touch freed memory. Once the scope has been dropped the
+    /// allocation is freed, and the trampoline must no longer be invoked with `data`.
+    pub fn register_extern_c<'scope, A: 'static, R: 'static, H: 'static>(
+        &'scope self,
+        c: impl (FnMut(A) -> R) + 'env,
+        register: impl FnOnce(unsafe extern "C" fn(A, *mut core::ffi::c_void) -> R, *mut core::ffi::c_void) -> H
+            + 'env,
+        deregister: impl FnOnce(H, *mut core::ffi::c_void) + 'env,
+    ) -> Registered<'env, 'scope> {
+        let c: Box<dyn FnMut(A) -> R> = unsafe { transmute_lifetime(Box::new(c)) };
+        let c = Box::new(core::cell::RefCell::new(Some(c)));
+        let data = Box::into_raw(c) as *mut core::ffi::c_void;
+        let handle = register(extern_c_trampoline::<A, R>, data);
+        let deregister = Rc::new(Deregister::new(Box::new(move || {
+            deregister(handle, data);
+            // SAFETY: `data` is still a live, unreclaimed `RefCell` (the `extern_c_allocs`
+            // entry pushed below is the only place that ever frees it, and it does so at
+            // most once, from `Scope::drop`), so dereferencing it here to clear the
+            // callback is sound; a stray call to the trampoline after this point finds
+            // `None` and panics rather than touching freed memory.
+            let cell = unsafe {
+                &*(data as *const core::cell::RefCell<Option<Box<dyn FnMut(A) -> R>>>)
+            };
+            cell.borrow_mut().take();
+        })));
+        self.callbacks.borrow_mut().push(deregister.clone());
+        self.extern_c_allocs.borrow_mut().push(Box::new(move || {
+            // SAFETY: `data` was produced by `Box::into_raw` above; this is the only place
+            // that ever reclaims it, and it runs at most once, from `Scope::drop`, after the
+            // `deregister` closure above (if it ran at all) has already cleared the callback.
+            drop(unsafe {
+                Box::from_raw(data as *mut core::cell::RefCell<Option<Box<dyn FnMut(A) -> R>>>)
+            });
+        }));
+        Registered {
+            deregister,
+            marker: core::marker::PhantomData,
+        }
+    }
+
+    /// Spawn a child `Scope<'env>` and pass it to `f`. Callbacks registered on the child are
+    /// owned by the child: they are de-registered, in [LIFO order](#impl-Drop), when the
+    /// child scope is dropped at the end of this call, rather than waiting for `self` to drop.
+    ///
+    /// This cleanup is purely structural: `child` only ever hands out a `&Scope` to `f`, so
+    /// the child `Scope` itself lives on this call's own stack frame and is always dropped
+    /// when it returns, whether or not `f` leaks whatever `&Scope` borrows it produced.
+    pub fn child<'scope, R>(&'scope self, f: impl FnOnce(&Scope<'env>) -> R) -> R {
+        let child = Scope::<'env>::new_child(self);
+        f(&child)
+    }
+
+    /// Store `value` as context that [use_context](#method.use_context) can retrieve by type,
+    /// from this scope or from any of its descendant [child](#method.child) scopes. Calling
+    /// this again with the same `T` shadows the previous value for subsequent lookups, without
+    /// dropping it early; both are dropped together when the scope is.
+    ///
+    /// `value` must be `'static` (an `Rc`, an `Arc`, or another owned value), not a borrow of
+    /// `'env`: lookups key on `TypeId`, which itself requires `T: 'static`, so there is no
+    /// sound way to accept shorter-lived values here. A set of callbacks registered in the
+    /// same scope can still share state cheaply by each capturing a clone of the same `Rc`.
+    ///
+    /// Stored context is dropped when the scope is, after all of the scope's registered
+    /// callbacks have been de-registered, so callbacks never observe freed context.
+    pub fn provide_context<T: 'static>(&self, value: T) {
+        self.context
+            .borrow_mut()
+            .push((core::any::TypeId::of::<T>(), Box::new(value)));
+    }
+
+    /// Retrieve a value previously stored with [provide_context](#method.provide_context) on
+    /// this scope, falling back to the nearest ancestor [child](#method.child) scope that
+    /// provided one. Returns `None` if no scope in the chain has provided a `T`.
+    pub fn use_context<T: 'static>(&self) -> Option<&T> {
+        let type_id = core::any::TypeId::of::<T>();
+        let mut scope: *const Scope<'env> = self;
+        loop {
+            // SAFETY: `scope` is always either `self` or one of its ancestors, reached by
+            // following `parent` pointers set up by `new_child`; a child only exists while
+            // its parent is still on the stack (see `child`), so every scope visited here is
+            // still alive.
+            let current = unsafe { &*scope };
+            let found = current
+                .context
+                .borrow()
+                .iter()
+                .rev()
+                .find(|(id, _)| *id == type_id)
+                .map(|(_, value)| {
+                    let value: &T = value
+                        .downcast_ref()
+                        .expect("TypeId match implies downcast succeeds");
+                    // SAFETY: extends the borrow from the `RefCell::borrow()` guard above to
+                    // `self`'s own lifetime. Sound because the boxed value lives on the heap
+                    // and keeps a stable address across further `provide_context` calls (only
+                    // the `Vec` of entries may reallocate), and is only ever freed by
+                    // `Scope::drop`, which requires unique access to the scope and so cannot
+                    // run while this shared reference is outstanding.
+                    unsafe { &*(value as *const T) }
+                });
+            if found.is_some() {
+                return found;
+            }
+            match current.parent {
+                Some(parent) => scope = parent,
+                None => return None,
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn extern_c_trampoline<A: 'static, R: 'static>(
+    arg: A,
+    data: *mut core::ffi::c_void,
+) -> R {
+    // SAFETY: `data` is a live `*const RefCell<Option<Box<dyn FnMut(A) -> R>>>` for as long as
+    // the C side may still call this trampoline: `register_extern_c` only ever frees it from
+    // `Scope::drop`, well after `deregister` has cleared the callback, so a call that races
+    // past de-registration finds `None` and panics instead of dereferencing freed memory.
+    let cell = &*(data as *const core::cell::RefCell<Option<Box<dyn FnMut(A) -> R>>>);
+    (cell
+        .borrow_mut()
+        .as_mut()
+        .expect("Callback used after scope is unsafe"))(arg)
+}
+
+/// Generates a `register_N` method on `Scope` for the given argument list, mirroring
+/// [Scope::register](struct.Scope.html#method.register) but for closures of that arity.
+/// Each arity needs its own lifetime-transmute specialization since
+/// `Box<dyn FnMut($($ty),*) -> R>` is a distinct type per arity.
+macro_rules! impl_register_arity {
+    ($register:ident, $transmute:ident, ( $($arg:ident : $ty:ident),* )) => {
+        unsafe fn $transmute<'a, $($ty: 'static,)* R: 'static>(
+            value: Box<dyn FnMut($($ty),*) -> R + 'a>,
+        ) -> Box<dyn FnMut($($ty),*) -> R + 'static> {
+            core::mem::transmute(value)
+        }
+
+        impl<'env> Scope<'env> {
+            /// Same as [register](#method.register), but for closures of a different arity.
+            pub fn $register<'scope, $($ty: 'static,)* R: 'static, H: 'static>(
+                &'scope self,
+                c: impl (FnMut($($ty),*) -> R) + 'env,
+                register: impl FnOnce(Box<dyn FnMut($($ty),*) -> R>) -> H + 'env,
+                deregister: impl FnOnce(H) + 'env,
+            ) -> Registered<'env, 'scope> {
+                let c = unsafe { $transmute(Box::new(c)) };
+                let c = Rc::new(core::cell::RefCell::new(Some(c)));
+                let handle = {
+                    let c = c.clone();
+                    register(Box::new(move |$($arg: $ty),*| {
+                        (c.as_ref()
+                            .borrow_mut()
+                            .as_mut()
+                            .expect("Callback used after scope is unsafe"))($($arg),*)
+                    }))
+                };
+                let deregister = Rc::new(Deregister::new(Box::new(move || {
+                    deregister(handle);
+                    c.as_ref().borrow_mut().take();
+                })));
+                self.callbacks.borrow_mut().push(deregister.clone());
+                Registered {
+                    deregister,
+                    marker: core::marker::PhantomData,
+                }
+            }
+        }
+    };
 }
 
+impl_register_arity!(register0, transmute_lifetime0, ());
+impl_register_arity!(register2, transmute_lifetime2, (arg0: A, arg1: B));
+impl_register_arity!(register3, transmute_lifetime3, (arg0: A, arg1: B, arg2: C));
+impl_register_arity!(register4, transmute_lifetime4, (arg0: A, arg1: B, arg2: C, arg3: D));
+
+/// De-registers, in LIFO order: the last callback registered on this scope (via
+/// [register](#method.register) or [register_extern_c](#method.register_extern_c)) is the
+/// first to be de-registered. This matches how nested scopes expect their destruction order,
+/// since a later callback may depend on state set up by an earlier one. Callbacks registered
+/// on a [child](#method.child) scope are not part of this order: they are de-registered when
+/// the `child` call returns.
 impl<'env> Drop for Scope<'env> {
     fn drop(&mut self) {
         self.callbacks
             .borrow()
             .iter()
+            .rev()
             .for_each(|deregister| deregister.force());
+        self.extern_c_allocs
+            .borrow_mut()
+            .drain(..)
+            .for_each(|free| free());
     }
 }
 
@@ -193,6 +416,79 @@ pub async fn scope_async_local<'env, R>(
     f(&Scope::<'env>::new()).await
 }
 
+/// A wrapper that uses `'env`'s invariance to make a compile-time promise about `inner`:
+/// it is dropped before `'env` ends. Built by [new_unchecked](#method.new_unchecked), whose
+/// caller must uphold that promise; [scope_guaranteed](fn.scope_guaranteed.html) builds one
+/// this way.
+pub struct IsDropped<'env, T> {
+    inner: T,
+    marker: core::marker::PhantomData<fn(&'env ()) -> &'env ()>,
+}
+
+impl<'env, T> IsDropped<'env, T> {
+    /// Wrap `inner`, promising it is dropped before `'env` ends.
+    ///
+    /// # Safety
+    /// The caller must ensure `self` is dropped before `'env` ends (barring an abort, a
+    /// double panic, or `std::process::exit`, which this type cannot guard against).
+    pub unsafe fn new_unchecked(inner: T) -> Self {
+        Self {
+            inner,
+            marker: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<'env, T> core::ops::Deref for IsDropped<'env, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// A lower-overhead alternative to [Scope::register](struct.Scope.html#method.register) for
+/// callers who can live within a stricter API: rather than a runtime `RefCell<Option<...>>>`
+/// guard that panics if the `'static` trampoline is invoked after teardown, `c` is moved
+/// directly into the trampoline closure, with no extra allocation or borrow-check per call.
+///
+/// `f` is only ever given `&IsDropped<'env, Registered>`, a *borrow*, and never the
+/// `Registered` itself, so it cannot be squirrelled away or `std::mem::forget`-ed; `f` must
+/// return before `scope_guaranteed` does, at which point the borrow's referent is dropped.
+/// This proves, at compile time, that the `Registered` handle is torn down before `'env` ends.
+///
+/// # Safety
+/// Dropping `Registered` only runs `deregister`; on its own that is not proof the trampoline
+/// can no longer be called, since that depends entirely on `deregister` itself. The caller
+/// must ensure `deregister` truly stops the `'static` trampoline from ever being invoked
+/// again before it returns. Unlike [register](struct.Scope.html#method.register), there is
+/// no runtime guard here: a call that races past a `deregister` which merely forgot to
+/// unregister reads `c` after it has been freed.
+pub unsafe fn scope_guaranteed<'env, A: 'static, R: 'static, H: 'static, Ret>(
+    c: impl (FnMut(A) -> R) + 'env,
+    register: impl FnOnce(Box<dyn FnMut(A) -> R>) -> H + 'env,
+    deregister: impl FnOnce(H) + 'env,
+    f: impl for<'scope> FnOnce(&'scope IsDropped<'env, Registered<'env, 'scope>>) -> Ret,
+) -> Ret {
+    let c: Box<dyn FnMut(A) -> R> = unsafe { transmute_lifetime(Box::new(c)) };
+    let handle = register(c);
+    let deregister = Rc::new(Deregister::new(Box::new(move || deregister(handle))));
+    let registered = Registered {
+        deregister,
+        marker: core::marker::PhantomData,
+    };
+    // SAFETY: `registered` is only ever reachable through the `&IsDropped` handed to `f`
+    // below, which cannot escape `f`; `f` must return before this function does, so
+    // `registered` is dropped here, before `'env` ends.
+    let registered = unsafe { IsDropped::new_unchecked(registered) };
+    f(&registered)
+}
+
+/// A thread-safe counterpart to this module, built on `Arc`/`Mutex` instead of `Rc`/`RefCell`,
+/// for registering scoped callbacks with APIs that invoke them from another thread.
+#[cfg(feature = "sync")]
+pub mod sync;
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -237,6 +533,34 @@ mod tests {
         });
     }
 
+    #[test]
+    fn calling_extern_c() {
+        let stored = Rc::new(core::cell::RefCell::new(None));
+        let deregistered_with = Rc::new(core::cell::Cell::new(None));
+        scope(|scope| {
+            let registered = scope.register_extern_c(
+                |a| 2 * a,
+                {
+                    let stored = stored.clone();
+                    move |f, data| {
+                        stored.as_ref().borrow_mut().replace((f, data));
+                        data
+                    }
+                },
+                {
+                    let deregistered_with = deregistered_with.clone();
+                    move |handle, data| deregistered_with.set(Some((handle, data)))
+                },
+            );
+
+            let (f, data) = stored.as_ref().borrow().unwrap();
+            assert_eq!(unsafe { f(42, data) }, 2 * 42);
+
+            core::mem::drop(registered);
+        });
+        assert!(deregistered_with.get().is_some());
+    }
+
     #[test]
     fn drop_registered_causes_deregister() {
         let dropped = Rc::new(core::cell::Cell::new(false));
@@ -266,6 +590,142 @@ mod tests {
         assert!(dropped.as_ref().get());
     }
 
+    #[test]
+    fn deregisters_in_lifo_order() {
+        let order = Rc::new(core::cell::RefCell::new(Vec::new()));
+        scope(|scope| {
+            let _a = scope.register(|_| {}, register, {
+                let order = order.clone();
+                move |_| order.as_ref().borrow_mut().push(1)
+            });
+            let _b = scope.register(|_| {}, register, {
+                let order = order.clone();
+                move |_| order.as_ref().borrow_mut().push(2)
+            });
+            let _c = scope.register(|_| {}, register, {
+                let order = order.clone();
+                move |_| order.as_ref().borrow_mut().push(3)
+            });
+        });
+        assert_eq!(*order.as_ref().borrow(), [3, 2, 1]);
+    }
+
+    #[test]
+    fn child_scope_deregisters_before_parent_drops() {
+        let dropped = Rc::new(core::cell::Cell::new(false));
+        scope(|scope| {
+            scope.child(|child| {
+                let registered = child.register(|_| {}, register, {
+                    let dropped = dropped.clone();
+                    move |_| dropped.as_ref().set(true)
+                });
+
+                core::mem::forget(registered);
+                assert!(!dropped.as_ref().get());
+            });
+
+            // The child `Scope` has already been dropped here, before `scope` itself drops.
+            assert!(dropped.as_ref().get());
+        });
+    }
+
+    #[test]
+    fn use_context_returns_provided_value() {
+        scope(|scope| {
+            scope.provide_context(42i32);
+            assert_eq!(scope.use_context::<i32>(), Some(&42));
+            assert_eq!(scope.use_context::<&str>(), None);
+        });
+    }
+
+    #[test]
+    fn use_context_walks_up_to_parent_scopes() {
+        scope(|scope| {
+            scope.provide_context("from parent");
+            scope.child(|child| {
+                assert_eq!(child.use_context::<&str>(), Some(&"from parent"));
+
+                child.provide_context(7i32);
+                assert_eq!(child.use_context::<i32>(), Some(&7));
+                assert_eq!(scope.use_context::<i32>(), None);
+            });
+        });
+    }
+
+    #[test]
+    fn register0_calls_with_no_arguments() {
+        let stored = Rc::new(core::cell::RefCell::new(None));
+        scope(|scope| {
+            let registered = scope.register0(
+                || 42,
+                {
+                    let stored = stored.clone();
+                    move |callback| {
+                        stored.as_ref().borrow_mut().replace(callback);
+                    }
+                },
+                |_| {},
+            );
+
+            assert_eq!((stored.as_ref().borrow_mut().as_mut().unwrap())(), 42);
+
+            core::mem::drop(registered);
+        });
+    }
+
+    #[test]
+    fn register3_calls_with_three_arguments() {
+        let stored = Rc::new(core::cell::RefCell::new(None));
+        scope(|scope| {
+            let registered = scope.register3(
+                |a: i32, b: i32, c: i32| a + b + c,
+                {
+                    let stored = stored.clone();
+                    move |callback| {
+                        stored.as_ref().borrow_mut().replace(callback);
+                    }
+                },
+                |_| {},
+            );
+
+            assert_eq!((stored.as_ref().borrow_mut().as_mut().unwrap())(1, 2, 3), 6);
+
+            core::mem::drop(registered);
+        });
+    }
+
+    #[test]
+    fn scope_guaranteed_calls_and_deregisters() {
+        let stored = Rc::new(core::cell::RefCell::new(None));
+        let dropped = Rc::new(core::cell::Cell::new(false));
+        // SAFETY: `deregister` below drops `stored`'s callback, so the trampoline is never
+        // reachable again once `scope_guaranteed` returns.
+        unsafe {
+            scope_guaranteed(
+                |a| 2 * a,
+                {
+                    let stored = stored.clone();
+                    move |callback| {
+                        stored.as_ref().borrow_mut().replace(callback);
+                    }
+                },
+                {
+                    let stored = stored.clone();
+                    let dropped = dropped.clone();
+                    move |_| {
+                        stored.as_ref().borrow_mut().take();
+                        dropped.as_ref().set(true)
+                    }
+                },
+                |_registered| {
+                    assert_eq!((stored.as_ref().borrow_mut().as_mut().unwrap())(21), 42);
+                    assert!(!dropped.as_ref().get());
+                },
+            );
+        }
+        assert!(dropped.as_ref().get());
+    }
+
     #[test]
     /// Note: catch_unwind not available with `no_std`,
     /// See https://github.com/rust-lang/rfcs/issues/2810
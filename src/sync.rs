@@ -0,0 +1,230 @@
+//! Thread-safe counterpart to the crate root, built on `Arc`/`Mutex` instead of
+//! `Rc`/`RefCell`, so the generated trampoline is `Send` and can be registered with
+//! APIs that invoke the callback from a worker thread (a thread-pool, an OS signal thread,
+//! etc.).
+//!
+//! See [scope_sync](fn.scope_sync.html), which mirrors
+//! [scope](../fn.scope.html) but requires `c` to be `Send`.
+
+use std::sync::{Arc, Mutex};
+
+unsafe fn transmute_lifetime<'a, A: 'static, R: 'static>(
+    value: Box<dyn FnMut(A) -> R + Send + 'a>,
+) -> Box<dyn FnMut(A) -> R + Send + 'static> {
+    core::mem::transmute(value)
+}
+
+struct SyncDeregister<'a>(Mutex<Option<Box<dyn FnOnce() + Send + 'a>>>);
+
+impl<'a> SyncDeregister<'a> {
+    fn new(f: Box<dyn FnOnce() + Send + 'a>) -> Self {
+        Self(Mutex::new(Some(f)))
+    }
+
+    fn force(&self) {
+        if let Some(f) = self.0.lock().unwrap().take() {
+            f();
+        }
+    }
+}
+
+impl<'a> Drop for SyncDeregister<'a> {
+    fn drop(&mut self) {
+        self.force();
+    }
+}
+
+/// A handle returned by [SyncScope::register](struct.SyncScope.html#method.register).
+/// When this handle is dropped, the callback is de-registered.
+pub struct SyncRegistered<'env, 'scope> {
+    deregister: Arc<SyncDeregister<'env>>,
+    marker: core::marker::PhantomData<&'scope ()>,
+}
+
+impl<'env, 'scope> Drop for SyncRegistered<'env, 'scope> {
+    fn drop(&mut self) {
+        self.deregister.force()
+    }
+}
+
+/// A `SyncScope` is used to register callbacks that may be invoked from another thread.
+/// See [SyncScope::register](struct.SyncScope.html#method.register).
+pub struct SyncScope<'env> {
+    callbacks: Mutex<Vec<Arc<SyncDeregister<'env>>>>,
+    marker: core::marker::PhantomData<&'env mut &'env ()>,
+}
+
+impl<'env> SyncScope<'env> {
+    fn new() -> Self {
+        Self {
+            callbacks: Mutex::new(Vec::new()),
+            marker: core::marker::PhantomData,
+        }
+    }
+
+    /// Register the function `c` with local lifetime `'env` using the `register` and
+    /// `deregister` functions that handle only `'static` lifetime functions.
+    /// The returned `SyncRegistered` object will, when dropped, invoke the `deregister`
+    /// function.
+    ///
+    /// Unlike [Scope::register](../struct.Scope.html#method.register), `c`, `deregister` and
+    /// the handle `H` must be `Send`, and the trampoline handed to `register` is
+    /// `Send`, so it may be called from a thread other than the one that registered it.
+    ///
+    /// If the `SyncRegistered` object is `std::mem::forget`-ed, `SyncScope::drop` will
+    /// perform the de-registration.
+    ///
+    /// *Note*: If the callback passed to the `register` function is invoked after
+    /// `deregister` has been invoked, the callback will `panic!`. Taking the lock to check
+    /// and panic happens atomically with the deregister taking its own lock, so a concurrent
+    /// deregister racing an in-flight call is always resolved one way or the other.
+    pub fn register<'scope, A: 'static, R: 'static, H: Send + 'static>(
+        &'scope self,
+        c: impl (FnMut(A) -> R) + Send + 'env,
+        register: impl FnOnce(Box<dyn FnMut(A) -> R + Send>) -> H + 'env,
+        deregister: impl FnOnce(H) + Send + 'env,
+    ) -> SyncRegistered<'env, 'scope> {
+        let c = unsafe { transmute_lifetime(Box::new(c)) };
+        let c = Arc::new(Mutex::new(Some(c)));
+        let handle = {
+            let c = c.clone();
+            register(Box::new(move |arg| {
+                (c.lock()
+                    .unwrap()
+                    .as_mut()
+                    .expect("Callback used after scope is unsafe"))(arg)
+            }))
+        };
+        let deregister = Arc::new(SyncDeregister::new(Box::new(move || {
+            deregister(handle);
+            c.lock().unwrap().take();
+        })));
+        self.callbacks.lock().unwrap().push(deregister.clone());
+        SyncRegistered {
+            deregister,
+            marker: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<'env> Drop for SyncScope<'env> {
+    fn drop(&mut self) {
+        self.callbacks
+            .lock()
+            .unwrap()
+            .iter()
+            .for_each(|deregister| deregister.force());
+    }
+}
+
+/// Call `scope_sync` to receive a `SyncScope` instance that can be used to register functions
+/// with thread-safe registration APIs.
+/// See [SyncScope::register](struct.SyncScope.html#method.register).
+pub fn scope_sync<'env, R>(f: impl FnOnce(&SyncScope<'env>) -> R) -> R {
+    f(&SyncScope::<'env>::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register(callback: Box<dyn FnMut(i32) + Send>) -> Box<dyn FnMut(i32) + Send> {
+        callback
+    }
+
+    fn deregister(_callback: Box<dyn FnMut(i32) + Send>) {}
+
+    #[test]
+    fn it_works() {
+        let a = 42;
+        scope_sync(|scope| {
+            let registered = scope.register(
+                move |_| {
+                    let _b = a * a;
+                },
+                register,
+                deregister,
+            );
+
+            core::mem::drop(registered);
+        });
+    }
+
+    #[test]
+    fn calling_from_another_thread() {
+        let stored = Arc::new(Mutex::new(None));
+        scope_sync(|scope| {
+            let registered = scope.register(
+                |a| 2 * a,
+                {
+                    let stored = stored.clone();
+                    move |callback| {
+                        stored.lock().unwrap().replace(callback);
+                    }
+                },
+                |_| {},
+            );
+
+            let stored = stored.clone();
+            std::thread::spawn(move || {
+                assert_eq!((stored.lock().unwrap().as_mut().unwrap())(42), 2 * 42);
+            })
+            .join()
+            .unwrap();
+
+            core::mem::drop(registered);
+        });
+    }
+
+    #[test]
+    fn drop_registered_causes_deregister() {
+        let dropped = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        scope_sync(|scope| {
+            let registered = scope.register(|_| {}, register, {
+                let dropped = dropped.clone();
+                move |_| dropped.store(true, std::sync::atomic::Ordering::SeqCst)
+            });
+
+            core::mem::drop(registered);
+            assert!(dropped.load(std::sync::atomic::Ordering::SeqCst));
+        });
+    }
+
+    #[test]
+    fn leaving_scope_causes_deregister() {
+        let dropped = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        scope_sync(|scope| {
+            let registered = scope.register(|_| {}, register, {
+                let dropped = dropped.clone();
+                move |_| dropped.store(true, std::sync::atomic::Ordering::SeqCst)
+            });
+
+            core::mem::forget(registered);
+            assert!(!dropped.load(std::sync::atomic::Ordering::SeqCst));
+        });
+        assert!(dropped.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[test]
+    fn calling_static_callback_after_scope_panics() {
+        let res = std::panic::catch_unwind(|| {
+            let stored = Arc::new(Mutex::new(None));
+            scope_sync(|scope| {
+                let registered = scope.register(
+                    |_| {},
+                    {
+                        let stored = stored.clone();
+                        move |callback| {
+                            stored.lock().unwrap().replace(callback);
+                        }
+                    },
+                    |_| {},
+                );
+
+                core::mem::forget(registered);
+            });
+            (stored.lock().unwrap().as_mut().unwrap())(42);
+        });
+        assert!(res.is_err());
+    }
+}